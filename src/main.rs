@@ -12,23 +12,46 @@ fn main() {
     // 2. Create a new blockchain
     let mut blockchain = Blockchain::new();
 
-    // 3. Create and add more than 100 transactions to the mempool
+    // 3. Mint a starting balance to the chain's admin key, then mine it in
+    blockchain.mint(300_000).expect("mint must be accepted");
+    blockchain.add_block("Genesis Mint".to_string(), &miner_keypair);
+
+    // 4. Fund the miner and users from the admin's new balance, then mine that funding in
+    for (recipient_keypair, amount) in [(&miner_keypair, 200_000u64), (&user1_keypair, 50_000u64), (&user2_keypair, 50_000u64)] {
+        blockchain.fund(recipient_keypair.public_key().as_ref().to_vec(), amount).expect("funding transaction must be accepted");
+    }
+    blockchain.add_block("Genesis Funding".to_string(), &miner_keypair);
+
+    // 5. Create and add more than 100 transactions to the mempool
+    let recent_blockhash = blockchain.get_chain().last().unwrap().hash.clone();
+    let mut user1_nonce = 0u64;
+    let mut user2_nonce = 0u64;
     for i in 0..150 {
         let sender_keypair = if i % 2 == 0 { &user1_keypair } else { &user2_keypair };
         let receiver_public_key = if i % 2 == 0 { user2_keypair.public_key().as_ref().to_vec() } else { user1_keypair.public_key().as_ref().to_vec() };
+        let sender_nonce = if i % 2 == 0 { &mut user1_nonce } else { &mut user2_nonce };
+        *sender_nonce += 1;
 
         // Create and sign the transaction
-        let mut transaction = Transaction::new(sender_keypair.public_key().as_ref().to_vec(), receiver_public_key, i as u64 + 1);
+        let mut transaction = Transaction::new(
+            sender_keypair.public_key().as_ref().to_vec(),
+            receiver_public_key,
+            i as u64 + 1,
+            *sender_nonce,
+            recent_blockhash.clone(),
+        );
         transaction.sign(sender_keypair);
 
         // Add the transaction to the mempool
-        blockchain.add_transaction(transaction);
+        if let Err(e) = blockchain.add_transaction(transaction) {
+            println!("⚠️ Transaction rejected: {}", e);
+        }
     }
 
-    // 4. Mine all blocks until mempool is empty
+    // 6. Mine all blocks until mempool is empty
     blockchain.add_block("Block 1".to_string(), &miner_keypair);
 
-    // 5. Print the entire blockchain
+    // 7. Print the entire blockchain
     println!("\n🔗 Blockchain:");
     for block in blockchain.get_chain() {
         println!("Block {}: ", block.index);